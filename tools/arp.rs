@@ -0,0 +1,152 @@
+use std::net::Ipv4Addr;
+
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::Packet;
+use pnet::util::MacAddr;
+
+const ETH_HDR_LEN: usize = 14;
+const ARP_PACKET_LEN: usize = 28;
+
+/// If `frame` is an ARP request asking "who has `our_addr`", build the
+/// Ethernet+ARP reply frame asserting that `our_mac` owns it. Returns
+/// `None` for anything else (not ARP, not a request, or for a different
+/// target address) so the caller can fall through to its usual handling.
+pub fn build_reply(frame: &[u8], our_mac: MacAddr, our_addr: Ipv4Addr) -> Option<Vec<u8>> {
+    let eth = EthernetPacket::new(frame)?;
+    if eth.get_ethertype() != EtherTypes::Arp {
+        return None;
+    }
+    let req = ArpPacket::new(eth.payload())?;
+    if req.get_operation() != ArpOperations::Request || req.get_target_proto_addr() != our_addr {
+        return None;
+    }
+
+    let mut buf = vec![0u8; ETH_HDR_LEN + ARP_PACKET_LEN];
+    {
+        let mut eth_reply = MutableEthernetPacket::new(&mut buf[..ETH_HDR_LEN]).unwrap();
+        eth_reply.set_destination(eth.get_source());
+        eth_reply.set_source(our_mac);
+        eth_reply.set_ethertype(EtherTypes::Arp);
+    }
+    {
+        let mut reply = MutableArpPacket::new(&mut buf[ETH_HDR_LEN..]).unwrap();
+        reply.set_hardware_type(ArpHardwareTypes::Ethernet);
+        reply.set_protocol_type(EtherTypes::Ipv4);
+        reply.set_hw_addr_len(6);
+        reply.set_proto_addr_len(4);
+        reply.set_operation(ArpOperations::Reply);
+        reply.set_sender_hw_addr(our_mac);
+        reply.set_sender_proto_addr(our_addr);
+        reply.set_target_hw_addr(req.get_sender_hw_addr());
+        reply.set_target_proto_addr(req.get_sender_proto_addr());
+    }
+    Some(buf)
+}
+
+/// Build a gratuitous ARP announcement: a broadcast ARP request asserting
+/// `our_mac` owns `our_addr`, sent unprompted so peers refresh their caches.
+pub fn build_gratuitous(our_mac: MacAddr, our_addr: Ipv4Addr) -> Vec<u8> {
+    let mut buf = vec![0u8; ETH_HDR_LEN + ARP_PACKET_LEN];
+    {
+        let mut eth = MutableEthernetPacket::new(&mut buf[..ETH_HDR_LEN]).unwrap();
+        eth.set_destination(MacAddr::broadcast());
+        eth.set_source(our_mac);
+        eth.set_ethertype(EtherTypes::Arp);
+    }
+    {
+        let mut arp = MutableArpPacket::new(&mut buf[ETH_HDR_LEN..]).unwrap();
+        arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+        arp.set_protocol_type(EtherTypes::Ipv4);
+        arp.set_hw_addr_len(6);
+        arp.set_proto_addr_len(4);
+        arp.set_operation(ArpOperations::Request);
+        arp.set_sender_hw_addr(our_mac);
+        arp.set_sender_proto_addr(our_addr);
+        arp.set_target_hw_addr(MacAddr(0, 0, 0, 0, 0, 0));
+        arp.set_target_proto_addr(our_addr);
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUR_MAC: MacAddr = MacAddr(0, 3, 19, 0, 0, 1);
+    const OUR_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const PEER_MAC: MacAddr = MacAddr(0, 3, 19, 0, 0, 2);
+    const PEER_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    fn build_request(target_proto_addr: Ipv4Addr) -> Vec<u8> {
+        let mut buf = vec![0u8; ETH_HDR_LEN + ARP_PACKET_LEN];
+        {
+            let mut eth = MutableEthernetPacket::new(&mut buf[..ETH_HDR_LEN]).unwrap();
+            eth.set_destination(MacAddr::broadcast());
+            eth.set_source(PEER_MAC);
+            eth.set_ethertype(EtherTypes::Arp);
+        }
+        {
+            let mut arp = MutableArpPacket::new(&mut buf[ETH_HDR_LEN..]).unwrap();
+            arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+            arp.set_protocol_type(EtherTypes::Ipv4);
+            arp.set_hw_addr_len(6);
+            arp.set_proto_addr_len(4);
+            arp.set_operation(ArpOperations::Request);
+            arp.set_sender_hw_addr(PEER_MAC);
+            arp.set_sender_proto_addr(PEER_ADDR);
+            arp.set_target_hw_addr(MacAddr(0, 0, 0, 0, 0, 0));
+            arp.set_target_proto_addr(target_proto_addr);
+        }
+        buf
+    }
+
+    #[test]
+    fn build_reply_answers_a_request_for_our_address() {
+        let request = build_request(OUR_ADDR);
+        let reply = build_reply(&request, OUR_MAC, OUR_ADDR).expect("should reply");
+
+        let eth = EthernetPacket::new(&reply).unwrap();
+        assert_eq!(eth.get_source(), OUR_MAC);
+        assert_eq!(eth.get_destination(), PEER_MAC);
+        assert_eq!(eth.get_ethertype(), EtherTypes::Arp);
+
+        let arp = ArpPacket::new(eth.payload()).unwrap();
+        assert_eq!(arp.get_operation(), ArpOperations::Reply);
+        assert_eq!(arp.get_sender_hw_addr(), OUR_MAC);
+        assert_eq!(arp.get_sender_proto_addr(), OUR_ADDR);
+        assert_eq!(arp.get_target_hw_addr(), PEER_MAC);
+        assert_eq!(arp.get_target_proto_addr(), PEER_ADDR);
+    }
+
+    #[test]
+    fn build_reply_ignores_requests_for_a_different_address() {
+        let request = build_request(PEER_ADDR);
+        assert!(build_reply(&request, OUR_MAC, OUR_ADDR).is_none());
+    }
+
+    #[test]
+    fn build_reply_ignores_non_arp_frames() {
+        let mut frame = vec![0u8; ETH_HDR_LEN];
+        let mut eth = MutableEthernetPacket::new(&mut frame).unwrap();
+        eth.set_ethertype(EtherTypes::Ipv4);
+        drop(eth);
+
+        assert!(build_reply(&frame, OUR_MAC, OUR_ADDR).is_none());
+    }
+
+    #[test]
+    fn build_gratuitous_announces_our_address_to_the_broadcast_address() {
+        let frame = build_gratuitous(OUR_MAC, OUR_ADDR);
+
+        let eth = EthernetPacket::new(&frame).unwrap();
+        assert_eq!(eth.get_source(), OUR_MAC);
+        assert_eq!(eth.get_destination(), MacAddr::broadcast());
+        assert_eq!(eth.get_ethertype(), EtherTypes::Arp);
+
+        let arp = ArpPacket::new(eth.payload()).unwrap();
+        assert_eq!(arp.get_operation(), ArpOperations::Request);
+        assert_eq!(arp.get_sender_proto_addr(), OUR_ADDR);
+        assert_eq!(arp.get_target_proto_addr(), OUR_ADDR);
+    }
+}
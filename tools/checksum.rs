@@ -0,0 +1,52 @@
+/// Sum of the 16-bit big-endian words in `data`, left unfolded and
+/// uncomplemented so callers can accumulate several buffers (e.g. a
+/// pseudo-header followed by a datagram) before finishing the checksum.
+/// A trailing odd byte is padded with a zero byte, per RFC 1071.
+pub fn sum16(data: &[u8]) -> u32 {
+    let mut sum: u32 = 0;
+    let mut chunks = data.chunks_exact(2);
+    for word in &mut chunks {
+        sum += u16::from_be_bytes([word[0], word[1]]) as u32;
+    }
+    if let [last] = chunks.remainder() {
+        sum += u16::from_be_bytes([*last, 0]) as u32;
+    }
+    sum
+}
+
+/// Fold a running 32-bit checksum accumulator down into its final 16-bit
+/// one's-complement form.
+pub fn fold(mut sum: u32) -> u16 {
+    while (sum >> 16) != 0 {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+    !(sum as u16)
+}
+
+/// The Internet checksum (RFC 1071) of `data` on its own, with no
+/// pseudo-header contribution.
+pub fn inet_checksum(data: &[u8]) -> u16 {
+    fold(sum16(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sum16_adds_16_bit_words_and_pads_a_trailing_byte() {
+        assert_eq!(sum16(&[0x00, 0x01, 0x00, 0x02]), 3);
+        assert_eq!(sum16(&[0x00, 0x01, 0x01]), 1 + 0x0100);
+    }
+
+    #[test]
+    fn fold_carries_the_high_bits_back_in_and_complements() {
+        // 0x1_0001 folds to 0x0002, then complements to 0xfffd
+        assert_eq!(fold(0x1_0001), 0xfffd);
+    }
+
+    #[test]
+    fn inet_checksum_of_an_all_zero_buffer_is_all_ones() {
+        assert_eq!(inet_checksum(&[0, 0, 0, 0]), 0xffff);
+    }
+}
@@ -0,0 +1,152 @@
+use std::net::Ipv4Addr;
+
+use pnet::packet::ethernet::{EtherTypes, MutableEthernetPacket};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::MutableIpv4Packet;
+use pnet::packet::udp::MutableUdpPacket;
+use pnet::packet::Packet;
+use pnet::util::MacAddr;
+
+#[path = "checksum.rs"]
+mod checksum;
+
+const ETH_HDR_LEN: usize = 14;
+const IPV4_HDR_LEN: usize = 20;
+const UDP_HDR_LEN: usize = 8;
+
+/// Source/destination addressing for the IPv4 + UDP framing mode.
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4UdpOpts {
+    pub src_addr: Ipv4Addr,
+    pub dst_addr: Ipv4Addr,
+    pub src_port: u16,
+    pub dst_port: u16,
+}
+
+/// Wrap `payload` in an Ethernet + IPv4 + UDP frame addressed per `opts`,
+/// with correctly computed IPv4 header and UDP checksums, so the frame can
+/// cross a router and validate under a standard capture tool.
+pub fn build_frame(
+    eth_src: MacAddr,
+    eth_dst: MacAddr,
+    opts: &Ipv4UdpOpts,
+    payload: &[u8],
+) -> Vec<u8> {
+    let udp_len = UDP_HDR_LEN + payload.len();
+    let mut buf = vec![0u8; ETH_HDR_LEN + IPV4_HDR_LEN + udp_len];
+
+    {
+        let mut eth = MutableEthernetPacket::new(&mut buf[..ETH_HDR_LEN]).unwrap();
+        eth.set_source(eth_src);
+        eth.set_destination(eth_dst);
+        eth.set_ethertype(EtherTypes::Ipv4);
+    }
+
+    {
+        let mut ip =
+            MutableIpv4Packet::new(&mut buf[ETH_HDR_LEN..ETH_HDR_LEN + IPV4_HDR_LEN]).unwrap();
+        ip.set_version(4);
+        ip.set_header_length((IPV4_HDR_LEN / 4) as u8);
+        ip.set_total_length((IPV4_HDR_LEN + udp_len) as u16);
+        ip.set_ttl(64);
+        ip.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+        ip.set_source(opts.src_addr);
+        ip.set_destination(opts.dst_addr);
+        ip.set_checksum(checksum::inet_checksum(ip.packet()));
+    }
+
+    {
+        let mut udp = MutableUdpPacket::new(&mut buf[ETH_HDR_LEN + IPV4_HDR_LEN..]).unwrap();
+        udp.set_source(opts.src_port);
+        udp.set_destination(opts.dst_port);
+        udp.set_length(udp_len as u16);
+        udp.set_payload(payload);
+
+        let pseudo_header = udp_pseudo_header(opts, udp_len as u16);
+        let sum = checksum::sum16(&pseudo_header) + checksum::sum16(udp.packet());
+        // RFC 768: a computed UDP checksum of 0x0000 is transmitted as
+        // 0xffff instead, since 0x0000 on the wire means "no checksum".
+        udp.set_checksum(match checksum::fold(sum) {
+            0 => 0xffff,
+            sum => sum,
+        });
+    }
+
+    buf
+}
+
+fn udp_pseudo_header(opts: &Ipv4UdpOpts, udp_len: u16) -> [u8; 12] {
+    let mut hdr = [0u8; 12];
+    hdr[0..4].copy_from_slice(&opts.src_addr.octets());
+    hdr[4..8].copy_from_slice(&opts.dst_addr.octets());
+    hdr[9] = IpNextHeaderProtocols::Udp.0;
+    hdr[10..12].copy_from_slice(&udp_len.to_be_bytes());
+    hdr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::ipv4::Ipv4Packet;
+    use pnet::packet::udp::UdpPacket;
+
+    fn test_opts() -> Ipv4UdpOpts {
+        Ipv4UdpOpts {
+            src_addr: Ipv4Addr::new(10, 0, 0, 1),
+            dst_addr: Ipv4Addr::new(10, 0, 0, 2),
+            src_port: 9000,
+            dst_port: 9001,
+        }
+    }
+
+    #[test]
+    fn build_frame_sets_expected_ipv4_and_udp_header_fields() {
+        let opts = test_opts();
+        let payload = [0xaa, 0xbb, 0xcc];
+        let frame = build_frame(
+            MacAddr(1, 2, 3, 4, 5, 6),
+            MacAddr(6, 5, 4, 3, 2, 1),
+            &opts,
+            &payload,
+        );
+
+        let ip = Ipv4Packet::new(&frame[ETH_HDR_LEN..]).unwrap();
+        assert_eq!(ip.get_version(), 4);
+        assert_eq!(ip.get_header_length(), (IPV4_HDR_LEN / 4) as u8);
+        assert_eq!(
+            ip.get_total_length() as usize,
+            IPV4_HDR_LEN + UDP_HDR_LEN + payload.len()
+        );
+        assert_eq!(ip.get_ttl(), 64);
+        assert_eq!(ip.get_next_level_protocol(), IpNextHeaderProtocols::Udp);
+        assert_eq!(ip.get_source(), opts.src_addr);
+        assert_eq!(ip.get_destination(), opts.dst_addr);
+
+        let udp = UdpPacket::new(&frame[ETH_HDR_LEN + IPV4_HDR_LEN..]).unwrap();
+        assert_eq!(udp.get_source(), opts.src_port);
+        assert_eq!(udp.get_destination(), opts.dst_port);
+        assert_eq!(udp.get_length() as usize, UDP_HDR_LEN + payload.len());
+    }
+
+    #[test]
+    fn build_frame_never_emits_a_zero_udp_checksum() {
+        // this exact addressing + 2-byte payload folds to a computed
+        // checksum of 0x0000, which RFC 768 reserves to mean "no checksum"
+        let opts = Ipv4UdpOpts {
+            src_addr: Ipv4Addr::new(10, 0, 0, 1),
+            dst_addr: Ipv4Addr::new(10, 0, 0, 2),
+            src_port: 0,
+            dst_port: 0,
+        };
+        let payload = 0xebd7_u16.to_be_bytes();
+        let frame = build_frame(
+            MacAddr(1, 2, 3, 4, 5, 6),
+            MacAddr(6, 5, 4, 3, 2, 1),
+            &opts,
+            &payload,
+        );
+
+        let udp = UdpPacket::new(&frame[ETH_HDR_LEN + IPV4_HDR_LEN..]).unwrap();
+        assert_eq!(udp.get_checksum(), 0xffff);
+    }
+}
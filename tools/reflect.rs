@@ -1,46 +1,177 @@
-use pnet::datalink::Channel::Ethernet;
-use pnet::datalink::{self, Config};
+#[path = "seq.rs"]
+mod seq;
+// device.rs is shared with tpg.rs via #[path]; reflect only ever runs
+// against a real interface, so LoopbackDevice (tpg's loopback test harness)
+// is unused here.
+#[allow(dead_code)]
+#[path = "device.rs"]
+mod device;
+#[path = "arp.rs"]
+mod arp;
+
+use std::net::Ipv4Addr;
+use std::time::Instant;
+
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
+use pnet::packet::Packet;
+use pnet::util::MacAddr;
+
+use device::{Device, PnetDevice};
+use seq::Seq;
+
+const ETH_HDR_LEN: usize = 14;
+const UDP_HDR_LEN: usize = 8;
+
+/// Where packet_gen stamps its 4-byte sequence number, relative to the
+/// start of the frame: right after the Ethernet header for raw L2 framing,
+/// or after the Ethernet+IPv4+UDP headers when `--udp` framing is in use.
+/// Returns `None` for anything this reflector can't make sense of as TPG
+/// traffic — an ARP request/reply (routine on a live segment, especially
+/// with an `--analyze`-side `PROTO_ADDR` configured), or an IPv4 packet too
+/// short to hold a header — rather than guessing it's ours and reading
+/// garbage bytes as a sequence number.
+fn seq_offset(packet_raw: &[u8]) -> Option<usize> {
+    let eth = EthernetPacket::new(packet_raw)?;
+    match eth.get_ethertype() {
+        EtherTypes::Arp => None,
+        EtherTypes::Ipv4 => {
+            let ip = eth.payload();
+            let ihl = (*ip.first()? & 0x0f) as usize * 4;
+            if ip.len() < ihl {
+                return None;
+            }
+            Some(ETH_HDR_LEN + ihl + UDP_HDR_LEN)
+        }
+        // anything else is treated as raw TPG framing, which sets its
+        // ethertype to the payload size rather than a registered value
+        _ => Some(ETH_HDR_LEN),
+    }
+}
+
+fn usage() -> String {
+    format!(
+        "Usage: {} IFNAME [PROTO_ADDR] [--analyze]\n\n\
+         PROTO_ADDR, if given, is the IPv4 address this reflector answers\n\
+         ARP requests for (and announces via a gratuitous ARP on startup),\n\
+         so a peer can resolve it without a static ARP entry.\n\n\
+         With --analyze, don't reflect non-ARP frames back to the sender;\n\
+         instead track the sequence numbers packet_gen stamps into the\n\
+         first 4 payload bytes and report throughput, loss and reordering.",
+        std::env::args().next().unwrap_or("reflect".to_string())
+    )
+}
+
+#[derive(Default)]
+struct Stats {
+    frames: u64,
+    bytes: u64,
+    loss: u64,
+    reorder: u64,
+}
+
+impl Stats {
+    fn record(&mut self, packet_raw: &[u8], last_seen: &mut Option<Seq>, start: Instant) {
+        self.frames += 1;
+        self.bytes += packet_raw.len() as u64;
+
+        if let Some(offset) = seq_offset(packet_raw).filter(|&o| packet_raw.len() >= o + 4) {
+            let seq_bytes: [u8; 4] = packet_raw[offset..offset + 4].try_into().unwrap();
+            let seq = Seq(u32::from_be_bytes(seq_bytes));
+
+            if let Some(last) = *last_seen {
+                let expected = Seq(last.0.wrapping_add(1));
+                if expected.precedes(&seq) {
+                    // we jumped past where we expected to be: the
+                    // intervening sequence numbers were lost
+                    self.loss += expected.distance_to(&seq) as u64;
+                } else if seq != expected {
+                    // seq is behind where we expected: it arrived late
+                    self.reorder += 1;
+                }
+            }
+
+            if last_seen.is_none_or(|last| last.precedes(&seq)) {
+                *last_seen = Some(seq);
+            }
+        }
+
+        if self.frames.is_multiple_of(10_000) {
+            let elapsed = start.elapsed().as_secs_f64().max(f64::EPSILON);
+            eprintln!(
+                "{} frames, {} bytes ({:.0} fps, {:.0} Bps), {} lost, {} reordered",
+                self.frames,
+                self.bytes,
+                self.frames as f64 / elapsed,
+                self.bytes as f64 / elapsed,
+                self.loss,
+                self.reorder,
+            );
+        }
+    }
+}
+
+fn run(mut dev: impl Device, analyze: bool, arp_responder: Option<(MacAddr, Ipv4Addr)>) -> ! {
+    let mut stats = Stats::default();
+    let mut last_seen: Option<Seq> = None;
+    let start = Instant::now();
+
+    loop {
+        let packet_raw = match dev.receive() {
+            Ok(packet_raw) => packet_raw,
+            Err(e) => panic!("An error occurred while reading: {}", e),
+        };
+
+        if let Some((our_mac, our_addr)) = arp_responder {
+            if let Some(reply) = arp::build_reply(&packet_raw, our_mac, our_addr) {
+                let _ = dev.transmit(&reply);
+                continue;
+            }
+        }
+
+        if analyze {
+            stats.record(&packet_raw, &mut last_seen, start);
+        } else {
+            let _ = dev.transmit(&packet_raw);
+        }
+    }
+}
 
 fn main() {
     let ifname = std::env::args().nth(1);
     let ifname = if let Some(ifname) = ifname {
         ifname
     } else {
-        eprintln!(
-            "Usage: {} IFNAME",
-            std::env::args().next().unwrap_or("reflect".to_string())
-        );
+        eprintln!("{}", usage());
         std::process::exit(2)
     };
 
-    let interface = datalink::interfaces()
+    let args: Vec<String> = std::env::args().collect();
+    let analyze = args.iter().any(|arg| arg == "--analyze");
+    let proto_addr: Option<Ipv4Addr> = args.get(2).and_then(|arg| arg.parse().ok());
+
+    let interface = pnet::datalink::interfaces()
         .into_iter()
         .find(|iface| iface.name == ifname)
         .expect("Network interface not found");
 
-    let config = Config {
+    let arp_responder = proto_addr.map(|addr| {
+        let mac = interface
+            .mac
+            .expect("interface has no hardware address to answer ARP with");
+        (mac, addr)
+    });
+
+    let config = pnet::datalink::Config {
         // write_buffer_size: 64 * 1024 * 1024,
         read_buffer_size: 64 * 1024 * 1024,
         ..Default::default()
     };
 
-    let (mut tx, mut rx) = match datalink::channel(&interface, config) {
-        Ok(Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unhandled channel type"),
-        Err(e) => panic!(
-            "An error occurred when creating the datalink channel: {}",
-            e
-        ),
-    };
+    let mut dev = PnetDevice::open(&interface, config);
 
-    loop {
-        match rx.next() {
-            Ok(packet_raw) => {
-                tx.send_to(packet_raw, None);
-            }
-            Err(e) => {
-                panic!("An error occurred while reading: {}", e);
-            }
-        }
+    if let Some((our_mac, our_addr)) = arp_responder {
+        let _ = dev.transmit(&arp::build_gratuitous(our_mac, our_addr));
     }
+
+    run(dev, analyze, arp_responder);
 }
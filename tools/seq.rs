@@ -0,0 +1,46 @@
+/// A 32-bit sequence number embedded in TPG frame payloads by `packet_gen`.
+///
+/// Ordering wraps around per RFC 1982 serial-number arithmetic rather than
+/// plain integer comparison, so a receiver tracking a multi-minute run
+/// doesn't misbehave once the counter passes back through zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seq(pub u32);
+
+impl Seq {
+    /// `true` iff `self` comes strictly before `other` in sequence order.
+    pub fn precedes(&self, other: &Seq) -> bool {
+        (self.0.wrapping_sub(other.0) as i32) < 0
+    }
+
+    /// Forward distance from `self` to `other`: how many sequence numbers
+    /// elapse after `self` before reaching `other`. Only meaningful for
+    /// numbers within `i32::MAX` of each other.
+    pub fn distance_to(&self, other: &Seq) -> u32 {
+        other.0.wrapping_sub(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedes_orders_nearby_numbers_normally() {
+        assert!(Seq(1).precedes(&Seq(2)));
+        assert!(!Seq(2).precedes(&Seq(1)));
+        assert!(!Seq(1).precedes(&Seq(1)));
+    }
+
+    #[test]
+    fn precedes_handles_wraparound_at_the_u32_boundary() {
+        assert!(Seq(u32::MAX).precedes(&Seq(0)));
+        assert!(!Seq(0).precedes(&Seq(u32::MAX)));
+    }
+
+    #[test]
+    fn distance_to_wraps_forward_across_the_boundary() {
+        assert_eq!(Seq(u32::MAX).distance_to(&Seq(0)), 1);
+        assert_eq!(Seq(u32::MAX - 1).distance_to(&Seq(1)), 3);
+        assert_eq!(Seq(5).distance_to(&Seq(5)), 0);
+    }
+}
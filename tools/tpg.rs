@@ -1,19 +1,36 @@
+// seq.rs and device.rs are shared with reflect.rs via #[path], so each
+// binary gets its own copy and only exercises part of the shared surface:
+// tpg never reads back frames outside its loopback tests, and never needs
+// Seq's ordering helpers (those drive reflect's loss/reorder tracking).
+#[allow(dead_code)]
+#[path = "seq.rs"]
+mod seq;
+#[path = "l3.rs"]
+mod l3;
+#[allow(dead_code)]
+#[path = "device.rs"]
+mod device;
+
 use std::{
     io::{stderr, Write},
+    net::Ipv4Addr,
     str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Arc,
+    time::Duration,
 };
 
-use pnet::{datalink::Channel::Ethernet, packet::ethernet::EtherType, util::MacAddr};
-use pnet::{
-    datalink::{self, Config},
-    packet::ethernet::MutableEthernetPacket,
-};
+use pnet::{packet::ethernet::EtherType, util::MacAddr};
+use pnet::{datalink, packet::ethernet::MutableEthernetPacket};
 use rand::{seq::SliceRandom, Rng};
 
+use device::{Device, PnetDevice};
+use seq::Seq;
+
 fn usage<Writer: Write>(w: &mut Writer) {
     writeln!(
         w,
-        r#"Usage: {0} IFNAME CTRL [DATA]
+        r#"Usage: {0} IFNAME CTRL [DATA] [--udp SRC_IP:SRC_PORT DST_IP:DST_PORT]
 
 A virtual GPY111 test packet generator. The CTRL and DATA arguments accept
 16-bit values and correspond to the PHY_TPGCTRL and PHY_TPGDATA registers
@@ -22,14 +39,61 @@ respectively.
 NB: CTRL should always be a multiple of 3 to both enable & activate the TPG,
     and must also have bit 7 cleared (0).
 
+With --udp, the generated payload is wrapped in a real IPv4 + UDP datagram
+(with correct header checksums) addressed between SRC and DST, instead of
+being shipped as a raw L2 payload under a size-as-ethertype frame.
+
 Example:
     {0} veth1 0x22_73 0b1001_1111_01010101
+    {0} veth1 0x22_73 --udp 10.0.0.1:9000 10.0.0.2:9000
 "#,
         std::env::args().next().unwrap_or_else(|| "tpg".to_string())
     )
     .unwrap();
 }
 
+enum FramingMode {
+    Raw,
+    Ipv4Udp(l3::Ipv4UdpOpts),
+}
+
+fn parse_sockaddr(s: &str) -> Result<(Ipv4Addr, u16), String> {
+    let (addr, port) = s
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected IP:PORT, got {:?}", s))?;
+    let addr: Ipv4Addr = addr
+        .parse()
+        .map_err(|err| format!("couldn't read {:?} as an IPv4 address: {}", addr, err))?;
+    let port: u16 = port
+        .parse()
+        .map_err(|err| format!("couldn't read {:?} as a port: {}", port, err))?;
+    Ok((addr, port))
+}
+
+fn parse_framing(args: &[String]) -> Result<FramingMode, String> {
+    let pos = match args.iter().position(|arg| arg == "--udp") {
+        Some(pos) => pos,
+        None => return Ok(FramingMode::Raw),
+    };
+
+    let src = args
+        .get(pos + 1)
+        .ok_or("--udp requires SRC_IP:SRC_PORT DST_IP:DST_PORT")?;
+    let dst = args
+        .get(pos + 2)
+        .ok_or("--udp requires SRC_IP:SRC_PORT DST_IP:DST_PORT")?;
+
+    let (src_addr, src_port) = parse_sockaddr(src)?;
+    let (dst_addr, dst_port) = parse_sockaddr(dst)?;
+
+    Ok(FramingMode::Ipv4Udp(l3::Ipv4UdpOpts {
+        src_addr,
+        dst_addr,
+        src_port,
+        dst_port,
+    }))
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct Reg(pub u16);
 
@@ -91,7 +155,6 @@ impl FromStr for Ctrl {
 }
 
 impl Ctrl {
-    // TODO "Depending on the MODE, the TPG sends only 1 single packet or chunks of 10,000 packets until stopped"
     pub fn start(&self) -> bool {
         self.bits.0 & (1 << 1) != 0
     }
@@ -161,6 +224,8 @@ impl Ctrl {
 
 #[derive(Clone, Copy)]
 pub enum Mode {
+    // NB: a "chunk" is 10,000 frames; continuous mode sends chunk after
+    // chunk until SIGINT, which it checks for before every frame
     Continuous,
     // NB: "single" can also mean four sometimes in "debug dumping mode"
     // TODO: "debug dumping mode"
@@ -172,6 +237,17 @@ pub struct InterPacketGap {
     pub bitlen: u16,
 }
 
+impl InterPacketGap {
+    /// Convert this gap, expressed as a number of bit-times, into a
+    /// wall-clock delay for a link running at `link_bps` bits/second.
+    pub fn as_duration(&self, link_bps: u64) -> Duration {
+        if link_bps == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.bitlen as f64 / link_bps as f64)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum SizeOpt {
     // len is total frame size (including 14-byte ethernet header & 4-byte trailer)
@@ -225,6 +301,134 @@ impl Data {
     }
 }
 
+/// per p.75 of the datasheet, this is "{64,128,256,512,1024,1518,9600}-14 octets"
+/// but:
+/// - there is no way to specify 128 or 512 as the SIZE field?
+/// - the brackets indicating size stretch from the header start to FCS end,
+///   yet the header on its own is 14 octets; also, 1518-14=1504 which is very strange
+/// - the datasheet claims that the FCS is "2 octets", it ought to be 4?
+///
+/// TODO confirm with experimental result
+fn payload_size(sz: u16) -> usize {
+    sz as usize - 18
+}
+
+fn resolve_size(ctrl: &Ctrl, rng: &mut impl Rng) -> usize {
+    match ctrl.size() {
+        SizeOpt::Fixed { len } => payload_size(len),
+        SizeOpt::Random => payload_size(*[64_u16, 256, 1024, 1518].choose(rng).unwrap()),
+    }
+}
+
+/// Fill `payload` per `ctrl`'s configured pattern, then stamp a
+/// monotonically increasing sequence number over its first 4 bytes so a
+/// receiver can track loss/reordering.
+fn fill_payload(
+    payload: &mut [u8],
+    ctrl: &Ctrl,
+    data: &Data,
+    seq_no: &mut Seq,
+    rng: &mut impl Rng,
+) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte = match ctrl.ptype() {
+            // TODO these are all guesses too
+            PacketType::Random => rng.gen(),
+            PacketType::ByteInc => i as u8,
+            PacketType::Predefined => data.frame_data(),
+        }
+    }
+
+    if payload.len() >= 4 {
+        payload[0..4].copy_from_slice(&seq_no.0.to_be_bytes());
+    }
+    *seq_no = Seq(seq_no.0.wrapping_add(1));
+}
+
+/// Build one raw Ethernet frame (EtherType set to the payload size, per the
+/// existing convention) of `size` payload bytes.
+fn build_raw_frame(
+    ctrl: &Ctrl,
+    data: &Data,
+    size: usize,
+    seq_no: &mut Seq,
+    rng: &mut impl Rng,
+) -> Vec<u8> {
+    let mut buf = vec![0u8; size + 14 /* headers */];
+    {
+        let mut packet = MutableEthernetPacket::new(&mut buf[..]).unwrap();
+        packet.set_ethertype(EtherType::new(size as u16));
+        packet.set_source(data.src_addr());
+        packet.set_destination(data.dest_addr());
+    }
+    fill_payload(&mut buf[14..], ctrl, data, seq_no, rng);
+    buf
+}
+
+/// Best-effort read of the interface's negotiated link speed in bits/second,
+/// via the Linux `sysfs` `speed` attribute (reported in Mbit/s). Falls back
+/// to 1 Gbps if the interface doesn't expose one (e.g. a veth pair).
+fn link_speed_bps(ifname: &str) -> u64 {
+    std::fs::read_to_string(format!("/sys/class/net/{}/speed", ifname))
+        .ok()
+        .and_then(|s| s.trim().parse::<i64>().ok())
+        .filter(|&mbps| mbps > 0)
+        .map(|mbps| mbps as u64 * 1_000_000)
+        .unwrap_or(1_000_000_000)
+}
+
+fn run<D: Device>(mut dev: D, ifname: &str, ctrl: Ctrl, data: Data, framing: FramingMode) {
+    let mut rng = rand::thread_rng();
+    let mut seq_no = Seq(0);
+
+    // resolved per frame, not once up front, so SizeOpt::Random actually
+    // varies frame-to-frame over a sustained Mode::Continuous run
+    let mut send_one = |dev: &mut D| {
+        let size = resolve_size(&ctrl, &mut rng);
+        let frame = match &framing {
+            FramingMode::Raw => build_raw_frame(&ctrl, &data, size, &mut seq_no, &mut rng),
+            FramingMode::Ipv4Udp(opts) => {
+                let mut payload = vec![0u8; size];
+                fill_payload(&mut payload, &ctrl, &data, &mut seq_no, &mut rng);
+                l3::build_frame(data.src_addr(), data.dest_addr(), opts, &payload)
+            }
+        };
+        dev.transmit(&frame).unwrap();
+    };
+
+    match ctrl.mode() {
+        Mode::Single => {
+            send_one(&mut dev);
+        }
+        Mode::Continuous => {
+            let running = Arc::new(AtomicBool::new(true));
+            {
+                let running = Arc::clone(&running);
+                ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))
+                    .expect("failed to install SIGINT handler");
+            }
+
+            let gap = ctrl.ipgl().as_duration(link_speed_bps(ifname));
+            let mut sent: u64 = 0;
+            'chunks: while running.load(Ordering::SeqCst) {
+                // the datasheet specifies continuous mode emits in chunks of
+                // 10,000 frames until stopped, rather than one at a time
+                for _ in 0..10_000u32 {
+                    if !running.load(Ordering::SeqCst) {
+                        break 'chunks;
+                    }
+                    send_one(&mut dev);
+                    sent += 1;
+                    if !gap.is_zero() {
+                        std::thread::sleep(gap);
+                    }
+                }
+            }
+            eprintln!("stopped: sent {} frames", sent);
+        }
+    }
+}
+
 fn main() {
     let ifname = std::env::args().nth(1);
     let ifname = if let Some(ifname) = ifname {
@@ -247,7 +451,9 @@ fn main() {
         }
     };
 
-    let data: Data = match std::env::args().nth(3).map(|s| s.parse()) {
+    let args: Vec<String> = std::env::args().collect();
+
+    let data: Data = match args.get(3).filter(|s| *s != "--udp").map(|s| s.parse()) {
         None => Data::default(),
         Some(Ok(data)) => data,
         Some(Err(err)) => {
@@ -257,6 +463,15 @@ fn main() {
         }
     };
 
+    let framing = match parse_framing(&args) {
+        Ok(framing) => framing,
+        Err(err) => {
+            usage(&mut stderr());
+            eprintln!("{}", err);
+            std::process::exit(2)
+        }
+    };
+
     if !ctrl.should_run() {
         eprintln!(
             "ctrl register should be both enabled and started, saw: 0x{:x?}",
@@ -270,78 +485,86 @@ fn main() {
         .find(|iface| iface.name == ifname)
         .expect("Network interface not found");
 
-    let config = Config {
+    let config = datalink::Config {
         // write_buffer_size: 64 * 1024 * 1024,
         read_buffer_size: 64 * 1024 * 1024,
         ..Default::default()
     };
 
-    match ctrl.mode() {
-        Mode::Single => {}
-        Mode::Continuous => todo!("continuous mode"),
-    };
+    let dev = PnetDevice::open(&interface, config);
 
-    let (mut tx, _) = match datalink::channel(&interface, config) {
-        Ok(Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => panic!("Unhandled channel type"),
-        Err(e) => panic!(
-            "An error occurred when creating the datalink channel: {}",
-            e
-        ),
-    };
-
-    let mut rng = rand::thread_rng();
-
-    let payload_size = |sz: u16| -> usize {
-        // per p.75 of the datasheet, this is "{64,128,256,512,1024,1518,9600}-14 octets"
-        // but:
-        // - there is no way to specify 128 or 512 as the SIZE field?
-        // - the brackets indicating size stretch from the header start to FCS end,
-        //   yet the header on its own is 14 octets; also, 1518-14=1504 which is very strange
-        // - the datasheet claims that the FCS is "2 octets", it ought to be 4?
-
-        // TODO confirm with experimental result
-        sz as usize - 18
-    };
-    let size = match ctrl.size() {
-        SizeOpt::Fixed { len } => payload_size(len),
-        SizeOpt::Random => payload_size(*[64_u16, 256, 1024, 1518].choose(&mut rng).unwrap()),
-    };
-
-    let mut buf = vec![0u8; size + 14 /* headers */];
-    let mut packet_gen = |buf: &mut [u8]| {
-        let mut packet = MutableEthernetPacket::new(buf).unwrap();
-        // packet.set_ethertype(EtherType::new(opts.ethertype));
-        packet.set_ethertype(EtherType::new(size as u16));
-        packet.set_source(data.src_addr());
-        packet.set_destination(data.dest_addr());
+    run(dev, &ifname, ctrl, data, framing);
+}
 
-        for i in 0..size {
-            buf[14 + i] = match ctrl.ptype() {
-                // TODO these are all guesses too
-                PacketType::Random => rng.gen(),
-                PacketType::ByteInc => i as u8,
-                PacketType::Predefined => data.frame_data(),
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device::LoopbackDevice;
+
+    #[test]
+    fn byte_inc_pattern_survives_the_wire() {
+        // ptype = ByteInc (0b01 << 8), size = 64 (0b000 << 4), continuous & enabled
+        let ctrl: Ctrl = "0b0000_0001_0000_0011".parse().unwrap();
+        let data = Data::default();
+        let size = resolve_size(&ctrl, &mut rand::thread_rng());
+
+        let (mut near, mut far) = LoopbackDevice::pair();
+        let mut seq_no = Seq(0);
+        let frame = build_raw_frame(&ctrl, &data, size, &mut seq_no, &mut rand::thread_rng());
+        near.transmit(&frame).unwrap();
+        let received = far.receive().unwrap();
+
+        assert_eq!(received.len(), size + 14);
+        // the first 4 payload bytes carry the sequence number stamped by
+        // fill_payload, so the ByteInc pattern only holds from byte 4 on
+        for i in 4..size {
+            assert_eq!(received[14 + i], i as u8);
         }
-        // packet.set_payload(vals)
-    };
+    }
 
-    packet_gen(&mut buf[..]);
+    #[test]
+    fn fixed_9000_yields_expected_jumbo_frame_length() {
+        // size = 9000 (0b110 << 4), continuous & enabled
+        let ctrl: Ctrl = "0b0000_0000_0110_0011".parse().unwrap();
+        let data = Data::default();
+        assert!(matches!(ctrl.size(), SizeOpt::Fixed { len: 9000 }));
+        let size = resolve_size(&ctrl, &mut rand::thread_rng());
+
+        let (mut near, mut far) = LoopbackDevice::pair();
+        let mut seq_no = Seq(0);
+        let frame = build_raw_frame(&ctrl, &data, size, &mut seq_no, &mut rand::thread_rng());
+        near.transmit(&frame).unwrap();
+        let received = far.receive().unwrap();
+
+        // 9000 is the total on-wire frame size including the 14-byte
+        // ethernet header and 4-byte FCS trailer (see payload_size's doc
+        // comment), so the payload we build and send is 9000 - 18 bytes
+        assert_eq!(received.len(), 9000 - 18 + 14);
+    }
 
-    // TODO continuous mode?
-    // tx.build_and_send(10_000, packet_size, func)
+    #[test]
+    fn random_size_resolves_to_one_of_the_documented_sizes() {
+        // size = random (0b111 << 4), continuous & enabled
+        let ctrl: Ctrl = "0b0000_0000_0111_0011".parse().unwrap();
+        assert!(matches!(ctrl.size(), SizeOpt::Random));
+
+        for _ in 0..100 {
+            let size = resolve_size(&ctrl, &mut rand::thread_rng());
+            assert!([64_u16, 256, 1024, 1518]
+                .iter()
+                .any(|&len| size == payload_size(len)));
+        }
+    }
 
-    // loop {
-    //     match rx.next() {
-    //         Ok(packet_raw) => {
-    //             tx.send_to(packet_raw, None);
-    //         }
-    //         Err(e) => {
-    //             panic!("An error occurred while reading: {}", e);
-    //         }
-    //     }
-    // }
+    #[test]
+    fn ipg_as_duration_scales_with_link_speed() {
+        let ipg = InterPacketGap { bitlen: 96 };
 
-    tx.send_to(&buf[..], None).unwrap().unwrap();
+        // at 1 Gbps, 96 bit-times is 96ns
+        assert_eq!(ipg.as_duration(1_000_000_000), Duration::from_nanos(96));
+        // halving the link speed doubles the wall-clock gap
+        assert_eq!(ipg.as_duration(500_000_000), Duration::from_nanos(192));
+        // an unknown link speed (0) shouldn't stall the sender
+        assert_eq!(ipg.as_duration(0), Duration::ZERO);
+    }
 }